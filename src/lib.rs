@@ -1,7 +1,14 @@
-use std::{cell::UnsafeCell, collections::HashMap, future::Future, hash::Hash, sync::Arc};
+use std::{
+    cell::UnsafeCell,
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use parking_lot::{RwLock, RwLockUpgradableReadGuard};
-use tokio::sync::{futures::Notified, Notify};
+use tokio::sync::{futures::Notified, oneshot, Notify};
 
 /// SingleFlight represents a class of work and creates a space in which units of work
 /// can be executed with duplicate suppression.
@@ -18,8 +25,28 @@ impl<K, T> Default for SingleFlight<K, T> {
     }
 }
 
+impl<K, T> Clone for SingleFlight<K, T> {
+    // Cheap, shared clone: every clone shares the same in-flight map, so
+    // duplicate suppression spans all of them.
+    fn clone(&self) -> Self {
+        Self {
+            mapping: self.mapping.clone(),
+        }
+    }
+}
+
+// State of the shared slot. A slot starts Pending, and is transitioned exactly
+// once by the leader to either Done (the work produced a value) or Failed (the
+// leader was cancelled or its future panicked before producing one, or, in
+// `try_work`, its future returned an error).
+enum State<T> {
+    Pending,
+    Done(T),
+    Failed,
+}
+
 struct Shared<T> {
-    slot: UnsafeCell<Option<T>>,
+    slot: UnsafeCell<State<T>>,
     notify: Notify,
 }
 
@@ -29,18 +56,25 @@ unsafe impl<T> Sync for Shared<T> where T: Sync {}
 impl<T> Default for Shared<T> {
     fn default() -> Self {
         Self {
-            slot: UnsafeCell::new(None),
+            slot: UnsafeCell::new(State::Pending),
             notify: Notify::new(),
         }
     }
 }
 
 // BroadcastOnce consists of shared slot and notify.
-#[derive(Clone)]
 struct BroadcastOnce<T> {
     shared: Arc<Shared<T>>,
 }
 
+impl<T> Clone for BroadcastOnce<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
 impl<T> Default for BroadcastOnce<T> {
     fn default() -> Self {
         Self {
@@ -77,9 +111,16 @@ impl<T> BroadcastOnce<T> {
         }
     }
 
-    // Safety: do not call wake multiple times
+    // Safety: do not call wake multiple times, and do not mix with fail
     unsafe fn wake(&self, value: T) {
-        *self.shared.slot.get() = Some(value);
+        *self.shared.slot.get() = State::Done(value);
+        self.shared.notify.notify_waiters();
+    }
+
+    // Mark the slot as failed and release every waiter so they can re-elect a
+    // leader. Safety: do not call after wake, and only from the sole leader.
+    unsafe fn fail(&self) {
+        *self.shared.slot.get() = State::Failed;
         self.shared.notify.notify_waiters();
     }
 }
@@ -87,15 +128,115 @@ impl<T> BroadcastOnce<T> {
 // We already in WaitList, so wait will be fine, we won't miss
 // anything after Waiter generated.
 impl<T> BroadcastOnceWaiter<T> {
-    // Safety: first call wake, then call wait
-    async unsafe fn wait(self) -> T
+    // Wait for the leader to finish. Returns `Some(value)` on success, or `None`
+    // if the leader failed (cancelled, panicked, or returned an error from
+    // `try_work`) and the caller must re-elect.
+    // Safety: first call wake or fail, then call wait
+    async unsafe fn wait(self) -> Option<T>
     where
         T: Clone,
     {
         self.notified.await;
-        (*self.shared.slot.get())
-            .clone()
-            .expect("value not set unexpectedly")
+        match &*self.shared.slot.get() {
+            State::Done(value) => Some(value.clone()),
+            State::Failed => None,
+            State::Pending => unreachable!("slot still pending after notify"),
+        }
+    }
+}
+
+// RAII guard held by the leader branch. If the guard is dropped without being
+// completed (the leader was cancelled or panicked, or its `try_work` future
+// returned an error), `Drop` removes the key from the map, marks the
+// slot as failed and wakes every waiter so exactly one of them re-elects and
+// retries, guaranteeing forward progress without ever losing duplicate
+// suppression.
+struct LeaderGuard<K, T>
+where
+    K: Hash + Eq,
+{
+    key: K,
+    mapping: Arc<RwLock<HashMap<K, BroadcastOnce<T>>>>,
+    call: BroadcastOnce<T>,
+    completed: bool,
+}
+
+impl<K, T> LeaderGuard<K, T>
+where
+    K: Hash + Eq,
+{
+    fn new(
+        key: K,
+        mapping: Arc<RwLock<HashMap<K, BroadcastOnce<T>>>>,
+        call: BroadcastOnce<T>,
+    ) -> Self {
+        Self {
+            key,
+            mapping,
+            call,
+            completed: false,
+        }
+    }
+
+    // Disarm the guard once the leader has produced a value.
+    fn complete(&mut self) {
+        self.completed = true;
+    }
+}
+
+impl<K, T> Drop for LeaderGuard<K, T>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        if !self.completed {
+            remove_if_current(&self.mapping, &self.key, &self.call);
+            // Safety: the leader never completed, so wake was never called.
+            unsafe { self.call.fail() };
+        }
+    }
+}
+
+// Outcome of joining the map for a key: either subscribe to the current leader,
+// or become the leader ourselves. Computed under the lock so the non-Send
+// upgradable read guard never crosses an await point.
+enum Slot<T> {
+    Wait(BroadcastOnceWaiter<T>),
+    Lead(BroadcastOnce<T>),
+}
+
+// Remove `key` from the map only if it still maps to `call`. A concurrent
+// `forget` (followed by a fresh leader) can replace the entry under the same
+// key; this keeps a finishing or failing leader from evicting its successor.
+fn remove_if_current<K, T>(
+    mapping: &Arc<RwLock<HashMap<K, BroadcastOnce<T>>>>,
+    key: &K,
+    call: &BroadcastOnce<T>,
+) where
+    K: Hash + Eq,
+{
+    let mut m = mapping.write();
+    if let Some(existing) = m.get(key) {
+        if Arc::ptr_eq(&existing.shared, &call.shared) {
+            m.remove(key);
+        }
+    }
+}
+
+fn enter<K, T>(mapping: &Arc<RwLock<HashMap<K, BroadcastOnce<T>>>>, key: &K) -> Slot<T>
+where
+    K: Hash + Eq + Clone,
+{
+    // The lock is never held across an await point.
+    let m = mapping.upgradable_read();
+    match m.get(key) {
+        Some(call) => Slot::Wait(call.waiter()),
+        None => {
+            let call = BroadcastOnce::new();
+            let mut m = RwLockUpgradableReadGuard::upgrade(m);
+            m.insert(key.clone(), call.clone());
+            Slot::Lead(call)
+        }
     }
 }
 
@@ -111,51 +252,355 @@ impl<K, T> SingleFlight<K, T>
 where
     K: Hash + Eq + Clone,
 {
+    /// Forget the in-flight call for `key`, if any.
+    ///
+    /// The entry is removed from the map so that the next [`SingleFlight::work`]
+    /// for `key` starts a brand-new leader execution instead of joining the
+    /// current one. Callers that already hold a waiter keep their clone of the
+    /// shared slot and still receive the original result when the old leader
+    /// finishes; only callers arriving after this point are unbound from it.
+    pub fn forget(&self, key: &K) {
+        self.mapping.write().remove(key);
+    }
+
     /// Execute and return the value for a given function, making sure that only one
     /// operation is in-flight at a given moment. If a duplicate call comes in, that caller will
     /// wait until the original call completes and return the same value.
-    #[allow(clippy::await_holding_lock)]
+    ///
+    /// If the leader is cancelled or its future panics before completing, the key
+    /// is released and one of the waiting callers re-elects itself and runs its own
+    /// `func`, so waiters never hang and at most one execution runs concurrently.
     pub fn work<F, Fut>(&self, key: K, func: F) -> impl Future<Output = T>
     where
         F: FnOnce() -> Fut,
         Fut: Future<Output = T>,
         T: Clone,
     {
-        enum Either<L, R> {
-            Left(L),
-            Right(R),
-        }
+        let detailed = self.work_detailed(key, func);
+        async move { detailed.await.0 }
+    }
 
-        // here the lock does not across await
-        let m = self.mapping.upgradable_read();
-        let val = m.get(&key);
-        let either = match val {
-            Some(call) => {
-                let waiter = call.waiter();
-                drop(m);
-                Either::Left(waiter)
+    /// Like [`SingleFlight::work`], but also reports whether this caller executed
+    /// the work.
+    ///
+    /// The returned boolean is `true` only for the caller that actually ran
+    /// `func`, and `false` for callers that received a shared result. This lets
+    /// users attribute cache-miss cost, emit deduplication metrics, or perform
+    /// owner-only side effects such as writing through to a backing store.
+    pub fn work_detailed<F, Fut>(&self, key: K, func: F) -> impl Future<Output = (T, bool)>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+        T: Clone,
+    {
+        let mapping = self.mapping.clone();
+        // Join the map eagerly, before returning the future, so concurrent callers
+        // subscribe (or become leader) at `work` call time rather than at first poll.
+        let mut slot = Some(enter(&mapping, &key));
+        async move {
+            // Each caller owns its own `func`; it is consumed at most once, by the
+            // branch where this caller becomes the leader.
+            let mut func = Some(func);
+            loop {
+                let current = match slot.take() {
+                    Some(slot) => slot,
+                    None => enter(&mapping, &key),
+                };
+                match current {
+                    Slot::Wait(waiter) => match unsafe { waiter.wait().await } {
+                        Some(value) => return (value, false),
+                        // The leader failed; re-elect by looping.
+                        None => continue,
+                    },
+                    Slot::Lead(call) => {
+                        let func = func.take().expect("leader consumes func exactly once");
+                        let mut guard =
+                            LeaderGuard::new(key.clone(), mapping.clone(), call.clone());
+                        let output = func().await;
+                        guard.complete();
+                        remove_if_current(&mapping, &key, &call);
+                        unsafe { call.wake(output.clone()) };
+                        return (output, true);
+                    }
+                }
             }
-            None => {
-                let call = BroadcastOnce::new();
-                {
-                    let mut m = RwLockUpgradableReadGuard::upgrade(m);
-                    m.insert(key.clone(), call.clone());
+        }
+    }
+
+    /// Like [`SingleFlight::work`], but hand back a
+    /// [`oneshot::Receiver`](tokio::sync::oneshot::Receiver) that resolves to the
+    /// deduplicated result instead of an opaque future.
+    ///
+    /// This is the analogue of Go singleflight's `DoChan`: the receiver composes
+    /// naturally with [`tokio::time::timeout`], `tokio::select!`, or racing
+    /// several keys, so a caller can abandon a slow shared call without awaiting
+    /// the work future directly. The leader still runs `func` exactly once and
+    /// the result is fanned out to every subscriber; dropping a receiver only
+    /// abandons that caller's copy and never cancels the shared computation for
+    /// the others.
+    pub fn work_channel<F, Fut>(&self, key: K, func: F) -> oneshot::Receiver<T>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = T> + Send + 'static,
+        K: Send + Sync + 'static,
+        T: Clone + Send + Sync + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let work = self.work(key, func);
+        // The computation runs on its own task, so it survives a dropped receiver
+        // and stays shared across every subscriber.
+        tokio::spawn(async move {
+            // The receiver may be gone; its waiters still get the value via their
+            // own channels, so a send failure here is expected and ignored.
+            let _ = tx.send(work.await);
+        });
+        rx
+    }
+
+    /// Execute fallible work with duplicate suppression, sharing only successful
+    /// results.
+    ///
+    /// Unlike [`SingleFlight::work`], a failure is never broadcast: the leader
+    /// returns its own error directly, releases the key, and wakes the waiters,
+    /// who then re-elect a leader and run their own `func`. A successful value is
+    /// cloned and shared as usual. This keeps a single transient failure from
+    /// poisoning every concurrent caller and avoids requiring `E: Clone`.
+    pub fn try_work<F, Fut, E>(&self, key: K, func: F) -> impl Future<Output = Result<T, E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        T: Clone,
+    {
+        let mapping = self.mapping.clone();
+        // Join the map eagerly, as `work` does, so concurrent callers subscribe at
+        // call time rather than at first poll.
+        let mut slot = Some(enter(&mapping, &key));
+        async move {
+            let mut func = Some(func);
+            loop {
+                let current = match slot.take() {
+                    Some(slot) => slot,
+                    None => enter(&mapping, &key),
+                };
+                match current {
+                    Slot::Wait(waiter) => match unsafe { waiter.wait().await } {
+                        // A shared value only ever reaches us on success; a failed
+                        // leader wakes us into `None`, so we re-elect.
+                        Some(value) => return Ok(value),
+                        None => continue,
+                    },
+                    Slot::Lead(call) => {
+                        let func = func.take().expect("leader consumes func exactly once");
+                        let mut guard =
+                            LeaderGuard::new(key.clone(), mapping.clone(), call.clone());
+                        match func().await {
+                            Ok(value) => {
+                                guard.complete();
+                                remove_if_current(&mapping, &key, &call);
+                                unsafe { call.wake(value.clone()) };
+                                return Ok(value);
+                            }
+                            // Leave the guard armed: its `Drop` releases the key,
+                            // marks the slot failed and wakes the waiters to re-elect.
+                            Err(err) => return Err(err),
+                        }
+                    }
                 }
-                Either::Right((key, func(), self.mapping.clone(), call))
             }
-        };
+        }
+    }
+}
+
+// A completed, memoized result together with the instant it was stored, so its
+// age can be compared against a TTL on every access.
+struct Cached<T> {
+    value: T,
+    inserted_at: Instant,
+}
+
+/// A TTL cache layered on top of [`SingleFlight`].
+///
+/// Completed results are memoized for a caller-supplied duration, so repeated
+/// [`CachedSingleFlight::get_or_compute`] calls within the window skip execution
+/// entirely, while concurrent misses are still collapsed into a single call
+/// through the underlying group. The optional
+/// [`CachedSingleFlight::get_or_compute_stale`] adds stale-while-revalidate:
+/// within a grace window past the TTL the stale value is returned immediately
+/// and exactly one deduplicated background refresh is kicked off.
+///
+/// All state lives behind `Arc`, so the whole structure is cheap to clone and is
+/// `Send + Sync`; clones share the same cache and in-flight map.
+#[derive(Debug)]
+pub struct CachedSingleFlight<K, T> {
+    group: SingleFlight<K, T>,
+    cache: Arc<RwLock<HashMap<K, Cached<T>>>>,
+}
+
+impl<K, T> Default for CachedSingleFlight<K, T> {
+    fn default() -> Self {
+        Self {
+            group: SingleFlight::default(),
+            cache: Default::default(),
+        }
+    }
+}
+
+impl<K, T> Clone for CachedSingleFlight<K, T> {
+    fn clone(&self) -> Self {
+        Self {
+            group: self.group.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Cached<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cached")
+    }
+}
+
+impl<K, T> CachedSingleFlight<K, T> {
+    /// Create an empty cache with its own in-flight group.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<K, T> CachedSingleFlight<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    /// Return the cached value for `key` if it was stored less than `ttl` ago,
+    /// otherwise single-flight `func` to refresh it.
+    ///
+    /// Concurrent misses collapse into one execution; only the caller that
+    /// actually ran `func` writes the result back, so the stored `inserted_at`
+    /// reflects that single execution.
+    pub async fn get_or_compute<F, Fut>(&self, key: K, ttl: Duration, func: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.lookup(&key, ttl) {
+            return value;
+        }
+        let (value, owner) = self.group.work_detailed(key.clone(), func).await;
+        if owner {
+            self.store(key, value.clone());
+        }
+        value
+    }
+
+    /// Like [`CachedSingleFlight::get_or_compute`], but serves stale values while
+    /// revalidating in the background.
+    ///
+    /// A value younger than `ttl` is returned directly. A value whose age is
+    /// within `ttl + stale_window` is returned immediately as well, but a single
+    /// deduplicated background refresh is spawned to update the cache. Only once
+    /// the value is older than that does the caller block on a fresh computation.
+    pub fn get_or_compute_stale<F, Fut>(
+        &self,
+        key: K,
+        ttl: Duration,
+        stale_window: Duration,
+        func: F,
+    ) -> impl Future<Output = T>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = T> + Send + 'static,
+        K: Send + Sync + 'static,
+        T: Send + Sync + 'static,
+    {
+        // Resolve the cache state before the returned future is polled, so a
+        // stale hit can dispatch its background refresh right away.
+        let hit = self.peek(&key, ttl + stale_window);
+        let this = self.clone();
         async move {
-            match either {
-                Either::Left(waiter) => unsafe { waiter.wait().await },
-                Either::Right((key, fut, mapping, call)) => {
-                    let output = fut.await;
-                    mapping.write().remove(&key);
-                    unsafe { call.wake(output.clone()) };
-                    output
+            match hit {
+                Some((value, age)) if age < ttl => value,
+                Some((value, _)) => {
+                    // Stale but within the grace window: revalidate off to the
+                    // side (deduplicated through the group) and serve the stale
+                    // value now.
+                    this.spawn_refresh(key, func);
+                    value
+                }
+                None => {
+                    let (value, owner) = this.group.work_detailed(key.clone(), func).await;
+                    if owner {
+                        this.store(key, value.clone());
+                    }
+                    value
                 }
             }
         }
     }
+
+    /// Drop the memoized result for `key`, forcing the next access to recompute.
+    ///
+    /// This only evicts the cached value; an in-flight refresh is left alone and
+    /// will still populate the cache when it finishes.
+    pub fn invalidate(&self, key: &K) {
+        self.cache.write().remove(key);
+    }
+
+    /// Evict every memoized result older than `max_age`.
+    ///
+    /// Eviction is otherwise lazy (stale entries are simply overwritten on the
+    /// next refresh); call this from a periodic task to bound memory when keys
+    /// stop being requested.
+    pub fn sweep(&self, max_age: Duration) {
+        let now = Instant::now();
+        self.cache
+            .write()
+            .retain(|_, entry| now.duration_since(entry.inserted_at) < max_age);
+    }
+
+    // Return a fresh cached value (younger than `ttl`), if any.
+    fn lookup(&self, key: &K, ttl: Duration) -> Option<T> {
+        self.peek(key, ttl).map(|(value, _)| value)
+    }
+
+    // Return the cached value and its age if it is younger than `max_age`.
+    fn peek(&self, key: &K, max_age: Duration) -> Option<(T, Duration)> {
+        let now = Instant::now();
+        let cache = self.cache.read();
+        let entry = cache.get(key)?;
+        let age = now.duration_since(entry.inserted_at);
+        (age < max_age).then(|| (entry.value.clone(), age))
+    }
+
+    fn store(&self, key: K, value: T) {
+        self.cache.write().insert(
+            key,
+            Cached {
+                value,
+                inserted_at: Instant::now(),
+            },
+        );
+    }
+
+    // Run one deduplicated refresh in the background, writing the result back on
+    // completion. The spawned task keeps running even if every foreground caller
+    // goes away, so a started refresh always lands in the cache.
+    fn spawn_refresh<F, Fut>(&self, key: K, func: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = T> + Send + 'static,
+        K: Send + Sync + 'static,
+        T: Send + Sync + 'static,
+    {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let (value, owner) = this.group.work_detailed(key.clone(), func).await;
+            if owner {
+                this.store(key, value);
+            }
+        });
+    }
 }
 
 #[cfg(test)]
@@ -266,4 +711,390 @@ mod tests {
         tokio::time::sleep(Duration::from_millis(50)).await;
         assert_eq!(fut_late.await, "Result");
     }
+
+    #[tokio::test]
+    async fn work_detailed_reports_owner() {
+        let owner_count = AtomicUsize::default();
+
+        let group = SingleFlight::new();
+        let futures = FuturesUnordered::new();
+        for _ in 0..10 {
+            futures.push(group.work_detailed("key", || async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                "Result".to_string()
+            }));
+        }
+
+        let owners = futures
+            .map(|(out, owner)| {
+                assert_eq!(out, "Result");
+                usize::from(owner)
+            })
+            .fold(0usize, |acc, n| async move { acc + n })
+            .await;
+        owner_count.fetch_add(owners, AcqRel);
+
+        assert_eq!(
+            owner_count.load(Acquire),
+            1,
+            "exactly one caller should be the owner"
+        );
+    }
+
+    #[tokio::test]
+    async fn try_work_does_not_share_errors() {
+        let group = Arc::new(SingleFlight::new());
+        let call_counter = Arc::new(AtomicUsize::default());
+
+        // A non-Clone error type, to show `E: Clone` is not required.
+        struct NotCloneErr;
+
+        let leader_group = group.clone();
+        let counter = call_counter.clone();
+        let leader = tokio::spawn(async move {
+            leader_group
+                .try_work("key", || async move {
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    counter.fetch_add(1, AcqRel);
+                    Err::<String, _>(NotCloneErr)
+                })
+                .await
+        });
+
+        // Subscribe a waiter while the leader is still running.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let counter = call_counter.clone();
+        let result = group
+            .try_work("key", || async move {
+                counter.fetch_add(1, AcqRel);
+                Ok::<_, NotCloneErr>("recovered".to_string())
+            })
+            .await;
+
+        assert!(matches!(result, Ok(ref v) if v == "recovered"));
+        assert!(leader.await.unwrap().is_err());
+        // Both the failed leader and the re-elected waiter ran their own `func`.
+        assert_eq!(call_counter.load(Acquire), 2);
+    }
+
+    #[tokio::test]
+    async fn try_work_shares_success() {
+        let call_counter = AtomicUsize::default();
+
+        let group = SingleFlight::new();
+        let futures = FuturesUnordered::new();
+        for _ in 0..10 {
+            futures.push(group.try_work("key", || async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                call_counter.fetch_add(1, AcqRel);
+                Ok::<_, ()>("Result".to_string())
+            }));
+        }
+
+        assert!(
+            futures
+                .all(|out| async move { out == Ok("Result".to_string()) })
+                .await
+        );
+        assert_eq!(call_counter.load(Acquire), 1);
+    }
+
+    #[tokio::test]
+    async fn forget_starts_new_execution() {
+        let group = Arc::new(SingleFlight::new());
+        let call_counter = Arc::new(AtomicUsize::default());
+
+        let first_group = group.clone();
+        let counter = call_counter.clone();
+        let first = tokio::spawn(async move {
+            first_group
+                .work("key", || async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    counter.fetch_add(1, AcqRel);
+                    "first".to_string()
+                })
+                .await
+        });
+
+        // Let the first leader register, then invalidate it.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        group.forget(&"key");
+
+        let counter = call_counter.clone();
+        let second = group
+            .work("key", || async move {
+                counter.fetch_add(1, AcqRel);
+                "second".to_string()
+            })
+            .await;
+
+        // The caller arriving after forget is not bound to the old leader.
+        assert_eq!(second, "second");
+        // The original waiter-less leader still completes with its own result.
+        assert_eq!(first.await.unwrap(), "first");
+        assert_eq!(call_counter.load(Acquire), 2);
+    }
+
+    #[tokio::test]
+    async fn leader_panic_reelects_waiter() {
+        let group = Arc::new(SingleFlight::new());
+        let call_counter = Arc::new(AtomicUsize::default());
+
+        let leader_group = group.clone();
+        let leader = tokio::spawn(async move {
+            leader_group
+                .work("key", || async {
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    panic!("leader boom");
+                })
+                .await
+        });
+
+        // Subscribe a waiter while the leader is still running.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let counter = call_counter.clone();
+        let result = group
+            .work("key", || async move {
+                counter.fetch_add(1, AcqRel);
+                "recovered".to_string()
+            })
+            .await;
+
+        assert_eq!(result, "recovered");
+        assert_eq!(call_counter.load(Acquire), 1);
+        assert!(leader.await.is_err(), "leader future should have panicked");
+    }
+
+    #[tokio::test]
+    async fn work_channel_dedups() {
+        let call_counter = Arc::new(AtomicUsize::default());
+
+        let group = SingleFlight::new();
+        let mut receivers = Vec::new();
+        for _ in 0..10 {
+            let counter = call_counter.clone();
+            receivers.push(group.work_channel("key", move || async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            }));
+        }
+
+        for rx in receivers {
+            assert_eq!(rx.await.unwrap(), "Result");
+        }
+        assert_eq!(call_counter.load(Acquire), 1);
+    }
+
+    #[tokio::test]
+    async fn work_channel_dropped_receiver_does_not_cancel() {
+        let call_counter = Arc::new(AtomicUsize::default());
+
+        let group = SingleFlight::new();
+        // Subscribe two callers, then drop the first receiver before the work
+        // completes; the shared computation must still finish for the second.
+        let counter = call_counter.clone();
+        let rx_dropped = group.work_channel("key", move || {
+            let counter = counter.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            }
+        });
+        let rx_kept = group.work_channel("key", || async { panic!("leader already elected") });
+
+        drop(rx_dropped);
+        assert_eq!(rx_kept.await.unwrap(), "Result");
+        assert_eq!(call_counter.load(Acquire), 1);
+    }
+
+    #[tokio::test]
+    async fn work_channel_composes_with_timeout() {
+        let group = SingleFlight::new();
+        let rx = group.work_channel("key", || async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            "Result".to_string()
+        });
+
+        let timed_out = tokio::time::timeout(Duration::from_millis(10), rx).await;
+        assert!(timed_out.is_err(), "slow shared call should time out");
+    }
+
+    #[tokio::test]
+    async fn cached_memoizes_within_ttl() {
+        let call_counter = AtomicUsize::default();
+
+        let cache = CachedSingleFlight::new();
+        let ttl = Duration::from_secs(60);
+        for _ in 0..5 {
+            let result = cache
+                .get_or_compute("key", ttl, || async {
+                    call_counter.fetch_add(1, AcqRel);
+                    "Result".to_string()
+                })
+                .await;
+            assert_eq!(result, "Result");
+        }
+        assert_eq!(
+            call_counter.load(Acquire),
+            1,
+            "repeated calls within the ttl should reuse the cached value"
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_collapses_concurrent_misses() {
+        let call_counter = AtomicUsize::default();
+
+        let cache = CachedSingleFlight::new();
+        let ttl = Duration::from_secs(60);
+        let futures = FuturesUnordered::new();
+        for _ in 0..10 {
+            futures.push(cache.get_or_compute("key", ttl, || async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                call_counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            }));
+        }
+
+        assert!(futures.all(|out| async move { out == "Result" }).await);
+        assert_eq!(call_counter.load(Acquire), 1);
+    }
+
+    #[tokio::test]
+    async fn cached_recomputes_after_ttl() {
+        let call_counter = AtomicUsize::default();
+
+        let cache = CachedSingleFlight::new();
+        let ttl = Duration::from_millis(10);
+        cache
+            .get_or_compute("key", ttl, || async {
+                call_counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            })
+            .await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        cache
+            .get_or_compute("key", ttl, || async {
+                call_counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            })
+            .await;
+
+        assert_eq!(call_counter.load(Acquire), 2);
+    }
+
+    #[tokio::test]
+    async fn cached_stale_serves_and_revalidates() {
+        let call_counter = Arc::new(AtomicUsize::default());
+
+        let cache = CachedSingleFlight::new();
+        let ttl = Duration::from_millis(10);
+        let stale = Duration::from_secs(60);
+
+        let counter = call_counter.clone();
+        let first = cache
+            .get_or_compute_stale("key", ttl, stale, move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, AcqRel);
+                    "v1".to_string()
+                }
+            })
+            .await;
+        assert_eq!(first, "v1");
+
+        // Let the entry age past the ttl but stay within the stale window.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let counter = call_counter.clone();
+        let stale_hit = cache
+            .get_or_compute_stale("key", ttl, stale, move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, AcqRel);
+                    "v2".to_string()
+                }
+            })
+            .await;
+        // The stale value is served immediately.
+        assert_eq!(stale_hit, "v1");
+
+        // The background refresh eventually lands in the cache.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let fresh = cache
+            .get_or_compute_stale("key", ttl, stale, || async {
+                panic!("should be served from the refreshed cache")
+            })
+            .await;
+        assert_eq!(fresh, "v2");
+        assert_eq!(call_counter.load(Acquire), 2);
+    }
+
+    #[tokio::test]
+    async fn cached_invalidate_and_sweep() {
+        let call_counter = AtomicUsize::default();
+
+        let cache = CachedSingleFlight::new();
+        let ttl = Duration::from_secs(60);
+        cache
+            .get_or_compute("key", ttl, || async {
+                call_counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            })
+            .await;
+
+        cache.invalidate(&"key");
+        cache
+            .get_or_compute("key", ttl, || async {
+                call_counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            })
+            .await;
+        assert_eq!(call_counter.load(Acquire), 2, "invalidate forces recompute");
+
+        // Sweeping everything removes the freshly cached entry too.
+        cache.sweep(Duration::ZERO);
+        cache
+            .get_or_compute("key", ttl, || async {
+                call_counter.fetch_add(1, AcqRel);
+                "Result".to_string()
+            })
+            .await;
+        assert_eq!(call_counter.load(Acquire), 3);
+    }
+
+    #[tokio::test]
+    async fn leader_cancel_reelects_waiter() {
+        let group = Arc::new(SingleFlight::new());
+        let call_counter = Arc::new(AtomicUsize::default());
+
+        let leader_group = group.clone();
+        let counter = call_counter.clone();
+        let leader = tokio::spawn(async move {
+            leader_group
+                .work("key", || async move {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    counter.fetch_add(1, AcqRel);
+                    "leader".to_string()
+                })
+                .await
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // Cancel the leader before it can complete.
+        leader.abort();
+
+        let counter = call_counter.clone();
+        let result = group
+            .work("key", || async move {
+                counter.fetch_add(1, AcqRel);
+                "recovered".to_string()
+            })
+            .await;
+
+        assert_eq!(result, "recovered");
+        assert_eq!(call_counter.load(Acquire), 1);
+    }
 }